@@ -0,0 +1,117 @@
+//! Pluggable scheduling disciplines.
+//!
+//! [`WeightedFairQueue`](crate::WeightedFairQueue) is parameterized over a
+//! [`Discipline`], which decides both the virtual finish time assigned to an
+//! enqueued item and the order in which queued items are dispatched. The
+//! default [`Wfq`] reproduces classic weighted fair queuing;
+//! [`DeficitRoundRobin`] offers a deficit-round-robin alternative without
+//! forking the crate.
+
+use core::cmp::Ordering;
+use core::num::NonZeroU64;
+
+use crate::{FlowState, HeapItem};
+
+/// Decides the finish time of an item and the dispatch order of the normal
+/// queue. The queue stores one instance; each [`HeapItem`] carries the
+/// implementor only as a zero-sized phantom type parameter, and its ordering is
+/// applied through [`compare`](Discipline::compare).
+pub trait Discipline {
+    /// Returns the finish time to record for an item of `item_size` bytes and
+    /// the given `weight`, arriving on `flow` at the current `virtual_time`.
+    /// The queue stores the result as the flow's new `last_virtual_finish_time`
+    /// and as the item's heap key.
+    fn finish_time(
+        &mut self,
+        flow: &FlowState,
+        item_size: usize,
+        weight: NonZeroU64,
+        virtual_time: u64,
+    ) -> u64;
+
+    /// Compares two heap items, returning the ordering used by the normal
+    /// queue's max-heap (the `Greater` item is dispatched first).
+    fn compare<K, T>(a: &HeapItem<K, T, Self>, b: &HeapItem<K, T, Self>) -> Ordering
+    where
+        Self: Sized;
+}
+
+/// Classic weighted fair queuing: finish time grows by `item_size * weight` and
+/// the item with the smallest finish time is dispatched first.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Wfq;
+
+impl Discipline for Wfq {
+    fn finish_time(
+        &mut self,
+        flow: &FlowState,
+        item_size: usize,
+        weight: NonZeroU64,
+        _virtual_time: u64,
+    ) -> u64 {
+        flow.last_virtual_finish_time + item_size as u64 * weight.get()
+    }
+
+    fn compare<K, T>(a: &HeapItem<K, T, Self>, b: &HeapItem<K, T, Self>) -> Ordering {
+        a.virtual_finish_time
+            .cmp(&b.virtual_finish_time)
+            .reverse()
+    }
+}
+
+/// Deficit round robin: each flow accrues `quantum * weight` credit per round,
+/// and each item is placed `ceil(size / (quantum * weight))` rounds after the
+/// flow's previous item. The round index is used as the heap key, so
+/// lower-weight or bulkier flows are
+/// naturally deferred to later rounds.
+///
+/// This is a stateless *approximation* of textbook DRR rather than a faithful
+/// deficit counter. Each item's round is computed independently as
+/// `ceil(size / (quantum * weight))`, without carrying a per-flow deficit
+/// remainder between items — the [`Discipline::finish_time`] signature exposes
+/// only an immutable [`FlowState`] and no flow identity, so there is nowhere to
+/// accumulate that remainder. The practical consequence is that several
+/// sub-quantum items true DRR would drain within a single round are instead
+/// spread across consecutive rounds. The coarse round ordering — bulkier and
+/// lower-weight flows deferred — still holds.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone)]
+pub struct DeficitRoundRobin {
+    quantum: u64,
+}
+
+impl DeficitRoundRobin {
+    pub fn new(quantum: NonZeroU64) -> Self {
+        Self {
+            quantum: quantum.get(),
+        }
+    }
+}
+
+impl Default for DeficitRoundRobin {
+    fn default() -> Self {
+        // One Ethernet MTU of credit per round is the usual DRR starting point.
+        Self { quantum: 1500 }
+    }
+}
+
+impl Discipline for DeficitRoundRobin {
+    fn finish_time(
+        &mut self,
+        flow: &FlowState,
+        item_size: usize,
+        weight: NonZeroU64,
+        _virtual_time: u64,
+    ) -> u64 {
+        let credit_per_round = self.quantum.saturating_mul(weight.get()).max(1);
+        let rounds = (item_size as u64).div_ceil(credit_per_round);
+        flow.last_virtual_finish_time + rounds
+    }
+
+    fn compare<K, T>(a: &HeapItem<K, T, Self>, b: &HeapItem<K, T, Self>) -> Ordering {
+        a.virtual_finish_time
+            .cmp(&b.virtual_finish_time)
+            .reverse()
+    }
+}
@@ -0,0 +1,137 @@
+//! Fixed-capacity, allocation-free backing structures used by the `no_std`
+//! build of [`WeightedFairQueue`](crate::WeightedFairQueue).
+//!
+//! These are intentionally minimal: they expose only the slice of behaviour the
+//! queue relies on (a max-heap keyed by [`Ord`] and a linear-probe-free
+//! association list), mirroring the `heapless` const-generics approach without
+//! pulling in a dependency.
+
+/// A binary max-heap holding at most `N` elements, ordered by [`Ord`] exactly
+/// like [`std::collections::BinaryHeap`].
+#[derive(Debug)]
+pub struct FixedHeap<T, const N: usize> {
+    data: heapless::Vec<T, N>,
+}
+
+impl<T: Ord, const N: usize> FixedHeap<T, N> {
+    pub fn new() -> Self {
+        Self {
+            data: heapless::Vec::new(),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.data.len() == N
+    }
+
+    /// Pushes `value`, returning `Err(value)` if the heap is already at
+    /// capacity `N`.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        self.data.push(value)?;
+        self.sift_up(self.data.len() - 1);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let last = self.data.len().checked_sub(1)?;
+        self.data.swap(0, last);
+        let value = self.data.pop();
+        if !self.data.is_empty() {
+            self.sift_down(0);
+        }
+        value
+    }
+
+    fn sift_up(&mut self, mut pos: usize) {
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if self.data[pos] <= self.data[parent] {
+                break;
+            }
+            self.data.swap(pos, parent);
+            pos = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut pos: usize) {
+        let len = self.data.len();
+        loop {
+            let left = pos * 2 + 1;
+            let right = pos * 2 + 2;
+            let mut largest = pos;
+            if left < len && self.data[left] > self.data[largest] {
+                largest = left;
+            }
+            if right < len && self.data[right] > self.data[largest] {
+                largest = right;
+            }
+            if largest == pos {
+                break;
+            }
+            self.data.swap(pos, largest);
+            pos = largest;
+        }
+    }
+}
+
+/// A capacity-`N` association list standing in for
+/// [`std::collections::HashMap`] in the `no_std` build.
+#[derive(Debug)]
+pub struct FixedMap<K, V, const N: usize> {
+    entries: heapless::Vec<(K, V), N>,
+}
+
+impl<K: Eq, V, const N: usize> FixedMap<K, V, N> {
+    pub fn new() -> Self {
+        Self {
+            entries: heapless::Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.entries.len() == N
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.iter().any(|(k, _)| k == key)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.entries
+            .iter_mut()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Inserts `value` under `key`. The caller must ensure the map is not full
+    /// for a novel key; an insert past capacity is silently dropped, matching
+    /// the queue's pre-flight [`is_full`](Self::is_full) check.
+    pub fn insert(&mut self, key: K, value: V) {
+        if let Some(slot) = self.entries.iter_mut().find(|(k, _)| *k == key) {
+            slot.1 = value;
+        } else {
+            let _ = self.entries.push((key, value));
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let pos = self.entries.iter().position(|(k, _)| k == key)?;
+        Some(self.entries.swap_remove(pos).1)
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, (K, V)> {
+        self.entries.iter()
+    }
+}
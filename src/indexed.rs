@@ -0,0 +1,167 @@
+//! A vector-backed binary max-heap that tracks the position of every element
+//! by its sequence number, so that an arbitrary element can be located and
+//! removed in `O(log n)` rather than by draining the whole heap.
+//!
+//! This is the indexed-priority-queue technique: the heap array is paired with
+//! a `seqno -> index` map kept in sync on every sift, which lets
+//! [`WeightedFairQueue`](crate::WeightedFairQueue) cancel or re-prioritize a
+//! flow's queued items in place.
+
+use std::collections::HashMap;
+
+/// Implemented by heap elements that carry a unique sequence number, used as
+/// the stable key into the position map.
+pub(crate) trait Keyed {
+    fn key(&self) -> u64;
+}
+
+#[derive(Debug)]
+pub(crate) struct IndexedHeap<T> {
+    data: Vec<T>,
+    index: HashMap<u64, usize>,
+}
+
+impl<T: Ord + Keyed> IndexedHeap<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, value: T) {
+        let pos = self.data.len();
+        self.index.insert(value.key(), pos);
+        self.data.push(value);
+        self.sift_up(pos);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        let last = self.data.len().checked_sub(1)?;
+        self.data.swap(0, last);
+        let value = self.data.pop().expect("non-empty");
+        self.index.remove(&value.key());
+        if !self.data.is_empty() {
+            self.index.insert(self.data[0].key(), 0);
+            self.sift_down(0);
+        }
+        Some(value)
+    }
+
+    pub(crate) fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub(crate) fn get(&self, key: u64) -> Option<&T> {
+        self.index.get(&key).map(|&pos| &self.data[pos])
+    }
+
+    pub(crate) fn get_mut(&mut self, key: u64) -> Option<&mut T> {
+        let pos = *self.index.get(&key)?;
+        Some(&mut self.data[pos])
+    }
+
+    /// Removes the element with `key`, restoring heap order by swapping it with
+    /// the last element and sifting the moved element back into place.
+    pub(crate) fn remove(&mut self, key: u64) -> Option<T> {
+        let pos = self.index.remove(&key)?;
+        let last = self.data.len() - 1;
+        self.data.swap(pos, last);
+        let value = self.data.pop().expect("non-empty");
+        if pos < self.data.len() {
+            self.index.insert(self.data[pos].key(), pos);
+            self.resift_at(pos);
+        }
+        Some(value)
+    }
+
+    /// Restores the heap invariant around the element keyed by `key` after its
+    /// ordering key was mutated in place.
+    pub(crate) fn resift(&mut self, key: u64) {
+        if let Some(&pos) = self.index.get(&key) {
+            self.resift_at(pos);
+        }
+    }
+
+    fn resift_at(&mut self, pos: usize) {
+        let up = self.sift_up(pos);
+        self.sift_down(up);
+    }
+
+    fn sift_up(&mut self, mut pos: usize) -> usize {
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if self.data[pos] <= self.data[parent] {
+                break;
+            }
+            self.swap(pos, parent);
+            pos = parent;
+        }
+        pos
+    }
+
+    fn sift_down(&mut self, mut pos: usize) -> usize {
+        let len = self.data.len();
+        loop {
+            let left = pos * 2 + 1;
+            let right = pos * 2 + 2;
+            let mut largest = pos;
+            if left < len && self.data[left] > self.data[largest] {
+                largest = left;
+            }
+            if right < len && self.data[right] > self.data[largest] {
+                largest = right;
+            }
+            if largest == pos {
+                return pos;
+            }
+            self.swap(pos, largest);
+            pos = largest;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.index.insert(self.data[a].key(), b);
+        self.index.insert(self.data[b].key(), a);
+        self.data.swap(a, b);
+    }
+}
+
+impl<T> IntoIterator for IndexedHeap<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+/// Serialized as a flat sequence of its elements; the heap array layout and
+/// position map are not persisted.
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for IndexedHeap<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.data.iter())
+    }
+}
+
+/// Rebuilds the heap by pushing each element, re-establishing order from the
+/// elements' own `Ord` rather than trusting the serialized layout.
+#[cfg(feature = "serde")]
+impl<'de, T> serde::Deserialize<'de> for IndexedHeap<T>
+where
+    T: serde::Deserialize<'de> + Ord + Keyed,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let elements = Vec::<T>::deserialize(deserializer)?;
+        let mut heap = Self::new();
+        for element in elements {
+            heap.push(element);
+        }
+        Ok(heap)
+    }
+}
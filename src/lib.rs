@@ -1,52 +1,126 @@
-use std::collections::{BinaryHeap, HashMap};
-use std::hash::Hash;
-use std::num::NonZeroU64;
+#![cfg_attr(feature = "no_std", no_std)]
 
+use core::hash::Hash;
+use core::marker::PhantomData;
+use core::num::NonZeroU64;
+
+mod discipline;
+
+pub use self::discipline::{DeficitRoundRobin, Discipline, Wfq};
+
+#[cfg(not(feature = "no_std"))]
+use std::collections::HashMap;
+
+#[cfg(feature = "no_std")]
+mod fixed;
+
+#[cfg(not(feature = "no_std"))]
+mod indexed;
+
+#[cfg(feature = "no_std")]
+use self::fixed::{FixedHeap, FixedMap};
+
+#[cfg(not(feature = "no_std"))]
+use self::indexed::{IndexedHeap, Keyed};
+
+#[cfg(not(feature = "no_std"))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "K: serde::Serialize, T: serde::Serialize, D: serde::Serialize",
+        deserialize = "K: serde::Deserialize<'de> + Eq + Hash, T: serde::Deserialize<'de>, D: serde::Deserialize<'de> + Discipline"
+    ))
+)]
 #[derive(Debug)]
-pub struct WeightedFairQueue<K, T> {
-    items: BinaryHeap<HeapItem<K, T>>,
-    overflow: BinaryHeap<OverflowHeapItem<K, T>>,
+pub struct WeightedFairQueue<K, T, D = Wfq> {
+    items: IndexedHeap<HeapItem<K, T, D>>,
+    overflow: IndexedHeap<OverflowHeapItem<K, T, D>>,
     flows: HashMap<K, FlowState>,
+    flow_seqnos: HashMap<K, Vec<u64>>,
+    discipline: D,
     queue_size: QueueSize,
     max_normal_queue_size: usize,
+    renormalize_threshold: u64,
     virtual_time: u64,
     seqno: u64,
 }
 
-impl<K, T> WeightedFairQueue<K, T>
+#[cfg(not(feature = "no_std"))]
+impl<K, T, D> WeightedFairQueue<K, T, D>
 where
     K: Clone + Eq + Hash,
     T: AsRef<[u8]>,
+    D: Discipline,
 {
-    pub fn new(max_normal_queue_size: usize) -> Self {
+    /// Virtual finish times are renormalized once they climb past this point,
+    /// half the `u64` range, leaving ample headroom before the next rescale
+    /// without risking overflow of `item_size * weight` in between.
+    pub const DEFAULT_RENORMALIZE_THRESHOLD: u64 = u64::MAX / 2;
+
+    pub fn new(max_normal_queue_size: usize) -> Self
+    where
+        D: Default,
+    {
+        Self::with_renormalize_threshold(max_normal_queue_size, Self::DEFAULT_RENORMALIZE_THRESHOLD)
+    }
+
+    /// Like [`new`](Self::new) but with an explicit renormalization threshold,
+    /// primarily so tests can force the rescale path with small numbers.
+    pub fn with_renormalize_threshold(max_normal_queue_size: usize, renormalize_threshold: u64) -> Self
+    where
+        D: Default,
+    {
+        Self::with_discipline(max_normal_queue_size, renormalize_threshold, D::default())
+    }
+
+    /// Like [`with_renormalize_threshold`](Self::with_renormalize_threshold) but
+    /// takes the scheduling `discipline` explicitly, for disciplines that carry
+    /// configuration such as [`DeficitRoundRobin`]'s quantum.
+    pub fn with_discipline(
+        max_normal_queue_size: usize,
+        renormalize_threshold: u64,
+        discipline: D,
+    ) -> Self {
         Self {
-            items: BinaryHeap::new(),
-            overflow: BinaryHeap::new(),
+            items: IndexedHeap::new(),
+            overflow: IndexedHeap::new(),
             flows: HashMap::new(),
+            flow_seqnos: HashMap::new(),
+            discipline,
             queue_size: QueueSize::new(),
             max_normal_queue_size,
+            renormalize_threshold,
             virtual_time: 0,
             seqno: 0,
         }
     }
 
     pub fn enqueue(&mut self, item: Item<K, T>) {
-        if !self.flows.contains_key(item.flow_key()) {
+        let threshold = self.renormalize_threshold;
+        let key = item.flow_key().clone();
+        if !self.flows.contains_key(&key) {
             let flow = FlowState {
                 last_virtual_finish_time: self.virtual_time,
                 queue_size: QueueSize::new(),
             };
-            self.flows.insert(item.flow_key().clone(), flow);
+            self.flows.insert(key.clone(), flow);
         }
         let item_size = item.data_size();
 
-        let flow = self.flows.get_mut(item.flow_key()).expect("unreachable");
-        flow.last_virtual_finish_time += item_size as u64 * item.weight.get();
+        let virtual_time = self.virtual_time;
+        let flow = self.flows.get_mut(&key).expect("unreachable");
+        let finish_time =
+            self.discipline
+                .finish_time(flow, item_size, item.weight, virtual_time);
+        flow.last_virtual_finish_time = finish_time;
 
+        let seqno = self.seqno;
         let item = HeapItem {
             inner: item,
-            seqno: self.seqno,
-            virtual_finish_time: flow.last_virtual_finish_time,
+            seqno,
+            virtual_finish_time: finish_time,
+            _discipline: PhantomData,
         };
         self.seqno += 1;
 
@@ -59,24 +133,31 @@ where
             self.queue_size.normal += item_size;
             self.items.push(item);
         }
+        self.flow_seqnos.entry(key).or_default().push(seqno);
+
+        // `last_virtual_finish_time` only ever grows, so the flow just touched
+        // is the one that can cross the threshold this round.
+        if finish_time > threshold {
+            self.renormalize();
+        }
     }
 
     pub fn dequeue(&mut self) -> Option<Item<K, T>> {
-        let item = if let Some(item) = self.items.pop() {
-            item
-        } else {
-            return None;
-        };
+        let item = self.items.pop()?;
 
         self.virtual_time = item.virtual_finish_time;
         self.queue_size.normal -= item.inner.data_size();
+        self.untrack_seqno(item.inner.flow_key(), item.seqno);
 
         let flow = self
             .flows
             .get_mut(item.inner.flow_key())
             .expect("unreachable");
         flow.queue_size.normal -= item.inner.data_size();
-        if flow.queue_size.normal == 0 {
+        // Keep the flow alive while it still has overflow items; removing it on
+        // `normal == 0` would strand those items' accounting and panic the
+        // promotion loop below when it credits the flow.
+        if flow.queue_size.total() == 0 {
             self.flows.remove(item.inner.flow_key());
         }
 
@@ -88,7 +169,7 @@ where
 
             let flow = self
                 .flows
-                .get_mut(item.inner.flow_key())
+                .get_mut(next.0.inner.flow_key())
                 .expect("unreachable");
             flow.queue_size.normal += next.0.inner.data_size();
             flow.queue_size.overflow -= next.0.inner.data_size();
@@ -109,8 +190,327 @@ where
     pub fn flows(&self) -> &HashMap<K, FlowState> {
         &self.flows
     }
+
+    /// Drops every queued item belonging to `flow_key` in `O(k log n)` (for `k`
+    /// queued items across both heaps) and forgets the flow, returning the
+    /// number of items dropped. Use this when a connection closes so its backlog
+    /// does not have to be drained through `dequeue`.
+    pub fn remove_flow(&mut self, flow_key: &K) -> usize {
+        let seqnos = self.flow_seqnos.remove(flow_key).unwrap_or_default();
+        let mut removed = 0;
+        for seqno in seqnos {
+            if let Some(item) = self.items.remove(seqno) {
+                self.queue_size.normal -= item.inner.data_size();
+                removed += 1;
+            } else if let Some(item) = self.overflow.remove(seqno) {
+                self.queue_size.overflow -= item.0.inner.data_size();
+                removed += 1;
+            }
+        }
+        self.flows.remove(flow_key);
+        removed
+    }
+
+    /// Re-prioritizes `flow_key` under `weight`, recomputing the virtual finish
+    /// time of each of its queued items from the flow's base finish time and
+    /// re-sifting the affected heap nodes so the new ordering takes effect
+    /// without draining the queue. No-op if the flow has nothing queued.
+    pub fn set_flow_weight(&mut self, flow_key: &K, weight: NonZeroU64) {
+        let mut seqnos = match self.flow_seqnos.get(flow_key) {
+            Some(seqnos) if !seqnos.is_empty() => seqnos.clone(),
+            _ => return,
+        };
+        seqnos.sort_unstable();
+
+        let virtual_time = self.virtual_time;
+
+        // The finish time just before the flow's earliest queued item is the
+        // fixed point the recomputation grows from; relative order within the
+        // flow is preserved regardless of the old weight. Recover it by undoing
+        // that item's contribution under its current weight, routed through the
+        // active discipline so non-WFQ models are inverted correctly. The
+        // subtraction saturates because [chunk0-4] renormalization may have
+        // clamped the item's finish time below the increment it grew by.
+        let (first_size, old_weight, first_vft) = {
+            let first = self.entry(seqnos[0]).expect("tracked seqno");
+            (
+                first.inner.data_size(),
+                first.inner.weight,
+                first.virtual_finish_time,
+            )
+        };
+        let first_increment =
+            self.discipline
+                .finish_time(&FlowState::based_at(0), first_size, old_weight, virtual_time);
+        let mut acc = first_vft.saturating_sub(first_increment);
+
+        for seqno in seqnos {
+            let item_size = self.entry(seqno).expect("tracked seqno").inner.data_size();
+            acc = self
+                .discipline
+                .finish_time(&FlowState::based_at(acc), item_size, weight, virtual_time);
+            self.reweight(seqno, weight, acc);
+        }
+
+        if let Some(flow) = self.flows.get_mut(flow_key) {
+            flow.last_virtual_finish_time = acc;
+        }
+    }
+
+    /// Returns the item `dequeue` would return next — the one with the smallest
+    /// `virtual_finish_time` — without popping it or perturbing `virtual_time`.
+    /// Lets a caller decide whether to service the queue at all.
+    pub fn peek(&self) -> Option<&Item<K, T>> {
+        self.items.peek().map(|item| &item.inner)
+    }
+
+    /// Iterates over every queued item, normal and overflow, in arbitrary
+    /// order, leaving scheduling state untouched.
+    pub fn iter(&self) -> impl Iterator<Item = &Item<K, T>> {
+        self.items
+            .iter()
+            .map(|item| &item.inner)
+            .chain(self.overflow.iter().map(|item| &item.0.inner))
+    }
+
+    /// Empties the queue, yielding every queued item and resetting
+    /// `virtual_time`, `seqno`, the flow table, and both heaps to their initial
+    /// state.
+    pub fn drain(&mut self) -> impl Iterator<Item = Item<K, T>> {
+        let items = std::mem::replace(&mut self.items, IndexedHeap::new());
+        let overflow = std::mem::replace(&mut self.overflow, IndexedHeap::new());
+        self.flows.clear();
+        self.flow_seqnos.clear();
+        self.queue_size = QueueSize::new();
+        self.virtual_time = 0;
+        self.seqno = 0;
+
+        items
+            .into_iter()
+            .map(|item| item.inner)
+            .chain(overflow.into_iter().map(|item| item.0.inner))
+    }
+
+    /// Shifts every virtual finish time down by the current floor and rebuilds
+    /// both heaps. The floor is the larger of `virtual_time` and the smallest
+    /// live finish time: keying off `virtual_time` alone would never rescale a
+    /// queue that only enqueues (and so never advances `virtual_time`), which is
+    /// precisely the long-lived case that overflows. Because the subtraction is
+    /// uniform and monotone, relative ordering is preserved; times that would
+    /// fall below the floor clamp to zero.
+    fn renormalize(&mut self) {
+        let min_live = self
+            .items
+            .iter()
+            .map(|item| item.virtual_finish_time)
+            .chain(self.overflow.iter().map(|item| item.0.virtual_finish_time))
+            .min();
+        let shift = self.virtual_time.max(min_live.unwrap_or(0));
+        if shift == 0 {
+            return;
+        }
+
+        let items = std::mem::replace(&mut self.items, IndexedHeap::new());
+        for mut item in items {
+            item.virtual_finish_time = item.virtual_finish_time.saturating_sub(shift);
+            self.items.push(item);
+        }
+
+        let overflow = std::mem::replace(&mut self.overflow, IndexedHeap::new());
+        for mut item in overflow {
+            item.0.virtual_finish_time = item.0.virtual_finish_time.saturating_sub(shift);
+            self.overflow.push(item);
+        }
+
+        for flow in self.flows.values_mut() {
+            flow.last_virtual_finish_time = flow.last_virtual_finish_time.saturating_sub(shift);
+        }
+        self.virtual_time = self.virtual_time.saturating_sub(shift);
+    }
+
+    fn untrack_seqno(&mut self, flow_key: &K, seqno: u64) {
+        if let Some(seqnos) = self.flow_seqnos.get_mut(flow_key) {
+            if let Some(pos) = seqnos.iter().position(|&s| s == seqno) {
+                seqnos.swap_remove(pos);
+            }
+            if seqnos.is_empty() {
+                self.flow_seqnos.remove(flow_key);
+            }
+        }
+    }
+
+    fn entry(&self, seqno: u64) -> Option<&HeapItem<K, T, D>> {
+        self.items
+            .get(seqno)
+            .or_else(|| self.overflow.get(seqno).map(|item| &item.0))
+    }
+
+    fn reweight(&mut self, seqno: u64, weight: NonZeroU64, virtual_finish_time: u64) {
+        if let Some(item) = self.items.get_mut(seqno) {
+            item.inner.weight = weight;
+            item.virtual_finish_time = virtual_finish_time;
+            self.items.resift(seqno);
+        } else if let Some(item) = self.overflow.get_mut(seqno) {
+            item.0.inner.weight = weight;
+            item.0.virtual_finish_time = virtual_finish_time;
+            self.overflow.resift(seqno);
+        }
+    }
+}
+
+/// A `no_std`, allocation-free `WeightedFairQueue` backed by fixed-capacity
+/// storage sized by the const generic `N`.
+///
+/// Each backing heap and the flow table hold at most `N` entries. When an
+/// `enqueue` would exceed that hard limit the item is handed back to the caller
+/// instead of the queue growing. `max_normal_queue_size` still bounds the
+/// *byte* size of the normal queue exactly as in the `std` build; `N` is the
+/// independent hard bound on the number of outstanding items per backing heap.
+///
+/// This `no_std` variant is a deliberately reduced subset of the `std` queue:
+/// it offers only `enqueue`/`dequeue`/`queue_size`/`flows`. The richer surface
+/// added for the allocating build — `remove_flow`, `set_flow_weight`,
+/// `peek`/`iter`/`drain`, virtual-time renormalization, serde snapshots, and
+/// pluggable [`Discipline`]s — is intentionally omitted here, since each leans
+/// on allocation or the `std` collections the fixed-capacity backing cannot
+/// provide. Enable the default (allocating) build for those.
+#[cfg(feature = "no_std")]
+#[derive(Debug)]
+pub struct WeightedFairQueue<K, T, const N: usize> {
+    items: FixedHeap<HeapItem<K, T>, N>,
+    overflow: FixedHeap<OverflowHeapItem<K, T>, N>,
+    flows: FixedMap<K, FlowState, N>,
+    queue_size: QueueSize,
+    max_normal_queue_size: usize,
+    virtual_time: u64,
+    seqno: u64,
+}
+
+#[cfg(feature = "no_std")]
+impl<K, T, const N: usize> WeightedFairQueue<K, T, N>
+where
+    K: Clone + Eq + Hash,
+    T: AsRef<[u8]>,
+{
+    pub fn new(max_normal_queue_size: usize) -> Self {
+        Self {
+            items: FixedHeap::new(),
+            overflow: FixedHeap::new(),
+            flows: FixedMap::new(),
+            queue_size: QueueSize::new(),
+            max_normal_queue_size,
+            virtual_time: 0,
+            seqno: 0,
+        }
+    }
+
+    /// Enqueues `item`, returning `Err(item)` unchanged if the backing heap the
+    /// item would land in (or the flow table, for a new flow) is already at its
+    /// hard capacity `N`.
+    pub fn enqueue(&mut self, item: Item<K, T>) -> Result<(), Item<K, T>> {
+        let item_size = item.data_size();
+        let overflowing = self.queue_size.normal + item_size > self.max_normal_queue_size;
+
+        // Reject before touching any flow state so the returned item leaves the
+        // queue exactly as it found it.
+        let new_flow = !self.flows.contains_key(item.flow_key());
+        if new_flow && self.flows.is_full() {
+            return Err(item);
+        }
+        let target_full = if overflowing {
+            self.overflow.is_full()
+        } else {
+            self.items.is_full()
+        };
+        if target_full {
+            return Err(item);
+        }
+
+        if new_flow {
+            let flow = FlowState {
+                last_virtual_finish_time: self.virtual_time,
+                queue_size: QueueSize::new(),
+            };
+            self.flows.insert(item.flow_key().clone(), flow);
+        }
+
+        let flow = self.flows.get_mut(item.flow_key()).expect("unreachable");
+        flow.last_virtual_finish_time += item_size as u64 * item.weight.get();
+
+        let item = HeapItem {
+            inner: item,
+            seqno: self.seqno,
+            virtual_finish_time: flow.last_virtual_finish_time,
+            _discipline: PhantomData,
+        };
+        self.seqno += 1;
+
+        if overflowing {
+            flow.queue_size.overflow += item_size;
+            self.queue_size.overflow += item_size;
+            let _ = self.overflow.push(OverflowHeapItem(item));
+        } else {
+            flow.queue_size.normal += item_size;
+            self.queue_size.normal += item_size;
+            let _ = self.items.push(item);
+        }
+        Ok(())
+    }
+
+    pub fn dequeue(&mut self) -> Option<Item<K, T>> {
+        let item = self.items.pop()?;
+
+        self.virtual_time = item.virtual_finish_time;
+        self.queue_size.normal -= item.inner.data_size();
+
+        let flow = self
+            .flows
+            .get_mut(item.inner.flow_key())
+            .expect("unreachable");
+        flow.queue_size.normal -= item.inner.data_size();
+        // See the `std` dequeue: a flow must outlive its overflow backlog.
+        if flow.queue_size.total() == 0 {
+            self.flows.remove(item.inner.flow_key());
+        }
+
+        while let Some(next) = self.overflow.pop() {
+            // Respect both the byte budget and the hard count capacity `N`: a
+            // promotion that would overflow `items` must leave the item in the
+            // overflow heap untouched, or the push below silently drops it while
+            // the accounting still credits it.
+            if self.items.is_full()
+                || self.queue_size.normal + next.0.inner.data_size() > self.max_normal_queue_size
+            {
+                let _ = self.overflow.push(next);
+                break;
+            }
+
+            let flow = self
+                .flows
+                .get_mut(next.0.inner.flow_key())
+                .expect("unreachable");
+            flow.queue_size.normal += next.0.inner.data_size();
+            flow.queue_size.overflow -= next.0.inner.data_size();
+
+            self.queue_size.normal += next.0.inner.data_size();
+            self.queue_size.overflow -= next.0.inner.data_size();
+
+            let _ = self.items.push(next.0);
+        }
+
+        Some(item.inner)
+    }
+
+    pub fn queue_size(&self) -> QueueSize {
+        self.queue_size.clone()
+    }
+
+    pub fn flows(&self) -> &FixedMap<K, FlowState, N> {
+        &self.flows
+    }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone)]
 pub struct Item<K, T> {
     flow_key: K,
@@ -153,6 +553,7 @@ where
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum IpPrecedence {
     P0,
@@ -181,12 +582,27 @@ impl IpPrecedence {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone)]
 pub struct FlowState {
     pub queue_size: QueueSize,
     pub last_virtual_finish_time: u64,
 }
 
+#[cfg(not(feature = "no_std"))]
+impl FlowState {
+    /// A transient, empty flow state positioned at `last_virtual_finish_time`,
+    /// used to drive [`Discipline::finish_time`] while recomputing finish times
+    /// in [`WeightedFairQueue::set_flow_weight`].
+    fn based_at(last_virtual_finish_time: u64) -> Self {
+        Self {
+            queue_size: QueueSize::new(),
+            last_virtual_finish_time,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone)]
 pub struct QueueSize {
     normal: usize,
@@ -214,54 +630,75 @@ impl QueueSize {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone)]
-struct HeapItem<K, T> {
+#[doc(hidden)]
+pub struct HeapItem<K, T, D = Wfq> {
     inner: Item<K, T>,
     seqno: u64,
     virtual_finish_time: u64,
+    // The discipline only selects the `Ord` impl applied to this item, so it is
+    // carried as a zero-sized phantom rather than as a value.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _discipline: PhantomData<D>,
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<K, T, D> Keyed for HeapItem<K, T, D> {
+    fn key(&self) -> u64 {
+        self.seqno
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<K, T, D> Keyed for OverflowHeapItem<K, T, D> {
+    fn key(&self) -> u64 {
+        self.0.seqno
+    }
 }
 
-impl<K, T> PartialEq for HeapItem<K, T> {
+impl<K, T, D> PartialEq for HeapItem<K, T, D> {
     fn eq(&self, other: &Self) -> bool {
         self.seqno == other.seqno
     }
 }
 
-impl<K, T> Eq for HeapItem<K, T> {}
+impl<K, T, D> Eq for HeapItem<K, T, D> {}
 
-impl<K, T> PartialOrd for HeapItem<K, T> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+impl<K, T, D: Discipline> PartialOrd for HeapItem<K, T, D> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<K, T> Ord for HeapItem<K, T> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.virtual_finish_time
-            .cmp(&other.virtual_finish_time)
-            .reverse()
+impl<K, T, D: Discipline> Ord for HeapItem<K, T, D> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        D::compare(self, other)
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone)]
-struct OverflowHeapItem<K, T>(HeapItem<K, T>);
+struct OverflowHeapItem<K, T, D = Wfq>(HeapItem<K, T, D>);
 
-impl<K, T> PartialEq for OverflowHeapItem<K, T> {
+impl<K, T, D> PartialEq for OverflowHeapItem<K, T, D> {
     fn eq(&self, other: &Self) -> bool {
         self.0 == other.0
     }
 }
 
-impl<K, T> Eq for OverflowHeapItem<K, T> {}
+impl<K, T, D> Eq for OverflowHeapItem<K, T, D> {}
 
-impl<K, T> PartialOrd for OverflowHeapItem<K, T> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+impl<K, T, D> PartialOrd for OverflowHeapItem<K, T, D> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<K, T> Ord for OverflowHeapItem<K, T> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+// Promotion out of the overflow heap is discipline-independent: it always
+// favours the highest-weight item, breaking ties by arrival order.
+impl<K, T, D> Ord for OverflowHeapItem<K, T, D> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
         self.0
             .inner
             .weight()
@@ -269,3 +706,162 @@ impl<K, T> Ord for OverflowHeapItem<K, T> {
             .then_with(|| self.0.seqno.cmp(&other.0.seqno).reverse())
     }
 }
+
+#[cfg(all(test, not(feature = "no_std")))]
+mod tests {
+    use super::*;
+
+    fn item(flow: &'static str, weight: u64, data: Vec<u8>) -> Item<&'static str, Vec<u8>> {
+        Item::new(flow, NonZeroU64::new(weight).expect("non-zero weight"), data)
+    }
+
+    /// Drain the queue into the sequence of flow keys `dequeue` yields.
+    fn dequeue_order<K: Clone + Eq + Hash, D: Discipline>(
+        queue: &mut WeightedFairQueue<K, Vec<u8>, D>,
+    ) -> Vec<K> {
+        let mut order = Vec::new();
+        while let Some(item) = queue.dequeue() {
+            order.push(item.flow_key().clone());
+        }
+        order
+    }
+
+    #[test]
+    fn remove_flow_drops_and_counts() {
+        let mut queue = WeightedFairQueue::<&str, Vec<u8>>::new(1_000);
+        queue.enqueue(item("a", 1, vec![0; 10]));
+        queue.enqueue(item("a", 1, vec![0; 10]));
+        queue.enqueue(item("b", 1, vec![0; 10]));
+
+        assert_eq!(queue.remove_flow(&"a"), 2);
+        assert_eq!(queue.remove_flow(&"a"), 0);
+        assert_eq!(queue.queue_size().total(), 10);
+        assert_eq!(dequeue_order(&mut queue), vec!["b"]);
+    }
+
+    #[test]
+    fn set_flow_weight_reorders() {
+        let mut queue = WeightedFairQueue::<&str, Vec<u8>>::new(1_000);
+        queue.enqueue(item("a", 1, vec![0; 10])); // finish 10
+        queue.enqueue(item("b", 5, vec![0; 10])); // finish 50
+
+        assert_eq!(queue.peek().map(|i| *i.flow_key()), Some("a"));
+
+        // Inflate a's weight so its queued item now finishes well after b's.
+        queue.set_flow_weight(&"a", NonZeroU64::new(100).unwrap());
+        assert_eq!(queue.peek().map(|i| *i.flow_key()), Some("b"));
+        assert_eq!(dequeue_order(&mut queue), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn set_flow_weight_after_renormalization_does_not_underflow() {
+        // A low threshold and heavy weight force a renormalization that clamps
+        // the earliest live item's finish time toward zero; reweighting must
+        // recover the flow's base defensively rather than underflowing.
+        let mut queue = WeightedFairQueue::<&str, Vec<u8>>::with_renormalize_threshold(1_000, 2_500);
+        for i in 0..5u8 {
+            queue.enqueue(item("a", 1_000, vec![i]));
+        }
+
+        queue.set_flow_weight(&"a", NonZeroU64::new(1).unwrap());
+        assert_eq!(dequeue_order(&mut queue), vec!["a"; 5]);
+    }
+
+    #[test]
+    fn peek_and_iter_do_not_mutate() {
+        let mut queue = WeightedFairQueue::<&str, Vec<u8>>::new(1_000);
+        queue.enqueue(item("a", 1, vec![0; 10]));
+        queue.enqueue(item("b", 1, vec![0; 20]));
+
+        let before = queue.queue_size().total();
+        assert_eq!(queue.peek().map(|i| *i.flow_key()), Some("a"));
+        assert_eq!(queue.peek().map(|i| *i.flow_key()), Some("a"));
+        assert_eq!(queue.iter().count(), 2);
+        assert_eq!(queue.queue_size().total(), before);
+    }
+
+    #[test]
+    fn drain_empties_and_resets() {
+        let mut queue = WeightedFairQueue::<&str, Vec<u8>>::new(1_000);
+        queue.enqueue(item("a", 1, vec![0; 10]));
+        queue.enqueue(item("b", 1, vec![0; 10]));
+
+        let drained: Vec<_> = queue.drain().collect();
+        assert_eq!(drained.len(), 2);
+        assert_eq!(queue.queue_size().total(), 0);
+        assert!(queue.peek().is_none());
+        assert!(queue.flows().is_empty());
+    }
+
+    #[test]
+    fn renormalize_preserves_order_for_enqueue_only_queue() {
+        // A tiny threshold with a heavy weight forces the rescale path without
+        // ever dequeuing, the long-lived case renormalization targets.
+        let mut queue = WeightedFairQueue::<&str, Vec<u8>>::with_renormalize_threshold(1_000, 2_500);
+        for i in 0..10u8 {
+            queue.enqueue(item("a", 1_000, vec![i]));
+        }
+
+        // Single flow: finish times are monotone in arrival, so dequeue is FIFO
+        // and must survive the in-flight renormalization untouched.
+        let mut seen = Vec::new();
+        while let Some(item) = queue.dequeue() {
+            seen.push(item.data()[0]);
+        }
+        assert_eq!(seen, (0..10u8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn overflow_promotion_survives_emptied_flow() {
+        // Normal capacity holds one 10-byte item; the rest spill to overflow.
+        let mut queue = WeightedFairQueue::<&str, Vec<u8>>::new(10);
+        queue.enqueue(item("a", 1, vec![0; 10]));
+        queue.enqueue(item("a", 1, vec![0; 10]));
+        queue.enqueue(item("a", 1, vec![0; 10]));
+
+        // Dequeuing the only normal item empties flow "a"'s normal queue while it
+        // still holds overflow; promotion must not panic and accounting holds.
+        assert_eq!(dequeue_order(&mut queue), vec!["a", "a", "a"]);
+        assert_eq!(queue.queue_size().total(), 0);
+    }
+
+    #[test]
+    fn deficit_round_robin_defers_bulky_flow() {
+        let mut queue = WeightedFairQueue::<&str, Vec<u8>, DeficitRoundRobin>::with_discipline(
+            1_000,
+            WeightedFairQueue::<&str, Vec<u8>, DeficitRoundRobin>::DEFAULT_RENORMALIZE_THRESHOLD,
+            DeficitRoundRobin::new(NonZeroU64::new(100).unwrap()),
+        );
+        queue.enqueue(item("big", 1, vec![0; 250])); // 3 rounds
+        queue.enqueue(item("small", 1, vec![0; 50])); // round 1
+        queue.enqueue(item("small", 1, vec![0; 50])); // round 2
+
+        // Despite arriving first, the bulky flow is dispatched behind the two
+        // small items it cannot cover within a single quantum.
+        assert_eq!(dequeue_order(&mut queue), vec!["small", "small", "big"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip_preserves_dequeue_order() {
+        fn owned(flow: &str, weight: u64, size: usize) -> Item<String, Vec<u8>> {
+            Item::new(
+                flow.to_owned(),
+                NonZeroU64::new(weight).unwrap(),
+                vec![0; size],
+            )
+        }
+
+        let mut queue = WeightedFairQueue::<String, Vec<u8>>::new(30);
+        queue.enqueue(owned("a", 1, 10));
+        queue.enqueue(owned("b", 4, 10));
+        queue.enqueue(owned("a", 1, 10));
+        queue.enqueue(owned("c", 2, 10)); // spills to overflow
+
+        let json = serde_json::to_string(&queue).expect("serialize");
+        let mut restored: WeightedFairQueue<String, Vec<u8>> =
+            serde_json::from_str(&json).expect("deserialize");
+
+        assert_eq!(dequeue_order(&mut queue), dequeue_order(&mut restored));
+    }
+}